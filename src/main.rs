@@ -9,6 +9,30 @@ const MAX_PLAYERS: PlayerIx = 26;
 
 type DieDots = u8;
 
+// A generous cap on turns per simulated playthrough in `analyze`, so a
+// board that can't actually be won doesn't spin a trial forever.
+const MAX_ANALYSIS_TURNS: usize = 10_000;
+
+// `fairest_next_placement` runs a full expectimax search (its own,
+// from-scratch memo cache) for every candidate cell pair, since each
+// candidate mutates the board differently and so can't share a cache with
+// the others. That's candidates * (candidates - 1) * 2 independent
+// searches (a start never pairs with itself as its end), and each one
+// is itself exponential in the requested turn depth, so this has to stay
+// small to keep `balance` answering in a few seconds rather than tens of
+// them. Capping the candidate set keeps it usable on any board size;
+// cells beyond the cap are spread evenly across the unoccupied list
+// rather than just the first ones, so the sample isn't biased toward one
+// end of the board.
+const MAX_BALANCE_CANDIDATES: usize = 5;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
 // 1. The input is a series of lines, each containing one command.
 #[cfg(test)]
 const SAMPLE_INPUT: &'static str = "
@@ -48,27 +72,40 @@ Player B won
 // standard input, passing each line to the `readFrom` function. At
 // the end of the input, it must print the state of the board on
 // standard output.
+
+// A malformed or semantically invalid line shouldn't crash a session
+// that's otherwise going fine - it's reported and play continues with
+// whatever state had already built up, read-validate-retry style.
 fn main() {
     use std::io;
     use std::io::BufRead;
 
-    let mut game = GameState::default();
+    let mut session = Session::default();
     let stdin = io::stdin();
 
     for line in stdin.lock().lines() {
         let line = line.expect("stdin failed?!");
-        game.apply(Command::readFrom(line))
+        match Command::readFrom(line) {
+            Ok(command) => if let Err(e) = session.apply(command) {
+                println!("error: {}", e)
+            },
+            Err(e) => println!("error: {}", e),
+        }
     }
 
-    println!("{}", game.print());
+    println!("{}", session.game.print());
 }
 
 
-#[derive(PartialEq, Eq, Debug, Default)]
+#[derive(Debug, Default, Clone)]
 struct GameState {
     board: BoardConfig,
     players: Vec<PlayerState>,
-    dice: Vec<DieDots>,
+    dice: DiceMode,
+    // Only set once a `seed N` command has run. Leaving this `None` keeps
+    // the default live-play behaviour of drawing from the OS's entropy
+    // source on every roll, so test runs must opt into determinism.
+    rng: Option<StdRng>,
     turn: usize,
 }
 
@@ -132,39 +169,85 @@ impl GameState {
     // commands, and hence any number of the resulting game
     // features. There can also be any number of turn commands, each
     // of which will run in turn.
-    pub fn apply(&mut self, command: Command) {
+
+    // Beyond what `Command::readFrom` already checked about the shape of
+    // a line, a command can still be nonsense relative to the game built
+    // up so far - a ladder past the end of a board that hasn't grown to
+    // fit it, or too many players. Those are reported the same way as a
+    // parse error instead of panicking, so a bad command doesn't take
+    // down a session that's otherwise in good shape.
+    pub fn apply(&mut self, command: Command) -> Result<(), ParseError> {
         use Command::*;
         use CellProperty::*;
 
         match command {
-            Board { columns: y, rows: x } => self.board.set_size(y, x),
-            Players(n) => self.set_player_count(n),
-            Dice(ds) => { self.dice = ds },
-            Ladder { starts: s, ends: e } =>
-                self.board.set_cell_prop(s, LadderStart { end: e }),
-            Snake { starts: s, ends: e } =>
-                self.board.set_cell_prop(s, SnakeStart { end: e }),
-            PowerUps { typ: pt, cells: cs } =>
+            Board { columns: y, rows: x } => self.board.set_size(y, x)?,
+            Players(n) => self.set_player_count(n)?,
+            Dice(ds) => self.dice = DiceMode::Fixed(ds),
+            DiceRandom { low, high } => {
+                if low > high {
+                    return Err(ParseError::InvalidRange { low, high })
+                }
+                self.dice = DiceMode::Random { low, high }
+            }
+            Seed(s) => self.rng = Some(StdRng::seed_from_u64(s)),
+            Ladder { starts: s, ends: e } => {
+                self.board.check_cell(s)?;
+                self.board.check_cell(e)?;
+                self.board.set_cell_prop(s, LadderStart { end: e });
+            }
+            Snake { starts: s, ends: e } => {
+                self.board.check_cell(s)?;
+                self.board.check_cell(e)?;
+                self.board.set_cell_prop(s, SnakeStart { end: e });
+            }
+            PowerUps { typ: pt, cells: cs } => {
+                for &c in &cs { self.board.check_cell(c)? }
                 for c in cs {
                     self.board.set_cell_prop(c, PowerUp(pt.clone()))
-                },
+                }
+            }
             Turns(qty) =>
                 for _ in 0..qty {
                     // println!("turn {} of {}", turn + 1, qty);
                     for which_player in 0..self.players.len() {
-                        if self.player_turn_wins(which_player) {
-                            return
+                        match self.player_turn(which_player) {
+                            // The board is degenerate (a bump cycle), so
+                            // there is nothing sensible left to play out.
+                            TurnOutcome::Won | TurnOutcome::LoopDetected => return Ok(()),
+                            TurnOutcome::Moved => {}
                         }
                     }
                 },
+            Analyze(trials) => println!("{}", self.analyze(trials)),
+            Balance(max_turns) => {
+                if self.players.is_empty() {
+                    return Err(ParseError::NoPlayers)
+                }
+                match self.fairest_next_placement(max_turns) {
+                    Some((placement, probs)) =>
+                        println!("{} {} {} ({})",
+                                 placement.kind.command_word(), placement.starts, placement.ends,
+                                 format_win_probs(&probs)),
+                    None => println!("no unoccupied cells available to place a feature"),
+                }
+            }
+            // `Session::apply` handles these itself - a game on its own
+            // has no win tally to reset or report - so they never reach
+            // a bare `GameState`.
+            Reset | Scoreboard => unreachable!("Reset/Scoreboard are handled by Session"),
         }
+        Ok(())
     }
 
-    fn set_player_count(&mut self, n: PlayerIx) {
-        assert!(n <= MAX_PLAYERS);
+    fn set_player_count(&mut self, n: PlayerIx) -> Result<(), ParseError> {
+        if n > MAX_PLAYERS {
+            return Err(ParseError::TooManyPlayers { requested: n })
+        }
         self.players = vec![
             PlayerState { loc: 1, ..PlayerState::default()};
             n as usize];
+        Ok(())
     }
 
     // A turn means each player, in order, rolls the dice, and then
@@ -180,7 +263,13 @@ impl GameState {
     // cell. When a bumped player lands on a cell, they get the action
     // associated with that cell, including winning, powerups, snakes,
     // ladders, or bumping yet another player.
-    pub fn player_turn_wins(&mut self, who: usize) -> bool {
+
+    // The set-up board is assumed not to produce bump loops, but a
+    // degenerate one (e.g. two snakes that feed each other) can make this
+    // chain revisit a cell forever. A `HashSet` of cells already landed on
+    // this turn catches that: a repeat ends the turn with
+    // `TurnOutcome::LoopDetected` instead of spinning.
+    pub fn player_turn(&mut self, who: usize) -> TurnOutcome {
         let (mut delta, start_loc) = {
             let die = self.roll_dice();
             // println!("start turn: player {} rolls {}",
@@ -190,19 +279,24 @@ impl GameState {
         };
         if start_loc + delta > self.board.size() {
             // println!("cannot move");
-            return false
+            return TurnOutcome::Moved
         }
 
         let mut current_player = who;
+        let mut visited: HashSet<CellIx> = HashSet::new();
 
         loop {
             let land_loc = {
                 let player = &mut self.players[current_player];
 
-                if self.board.move_wins(player, delta) { return true }
+                if self.board.move_wins(player, delta) { return TurnOutcome::Won }
                 player.loc
             };
 
+            if !visited.insert(land_loc) {
+                return TurnOutcome::LoopDetected
+            }
+
             let already = self.players.iter().enumerate()
                 .find(|&(ix, p)| p.loc == land_loc && ix != current_player);
             if let Some((which, _)) = already {
@@ -214,17 +308,211 @@ impl GameState {
         }
 
         // println!("board:\n{}\n{:?}", self.print(), self);
-        false
+        TurnOutcome::Moved
     }
 
-    // The sequence will repeat indefinitely - e.g. the example above
-    // would produce the sequence 1, 2, 2, 2, 2, 1, 2, 2, 2, 2, 1, 2,
-    // 2, ...
+    // With `DiceMode::Fixed`, the sequence will repeat indefinitely - e.g.
+    // the example above would produce the sequence 1, 2, 2, 2, 2, 1, 2, 2,
+    // 2, 2, 1, 2, 2, ... With `DiceMode::Random`, each roll is drawn
+    // uniformly from `low..=high`: from a seeded `StdRng` if a `seed`
+    // command has run, or straight from the OS's entropy source otherwise.
+    // An empty fixed sequence (the default) rolls uniformly from 1..=6 the
+    // same way.
     fn roll_dice(&mut self) -> DieDots {
-        let die = self.dice[self.turn % self.dice.len()];
+        let die = match self.dice {
+            DiceMode::Fixed(ref ds) if !ds.is_empty() => ds[self.turn % ds.len()],
+            // No `dice` command has run yet: roll a standard 1..=6 die, as
+            // `dice_faces` does for the solver, rather than dividing by zero.
+            DiceMode::Fixed(_) => match self.rng {
+                Some(ref mut rng) => rng.gen_range(1..=6),
+                None => rand::thread_rng().gen_range(1..=6),
+            },
+            DiceMode::Random { low, high } => match self.rng {
+                Some(ref mut rng) => rng.gen_range(low..=high),
+                None => rand::thread_rng().gen_range(low..=high),
+            },
+        };
         self.turn += 1;
         die
     }
+
+    // Runs `trials` independent playthroughs of the board/players/powerups
+    // as currently configured, each with its own freshly-randomized dice
+    // (the configured dice mode is irrelevant here - a fixed sequence
+    // would make every trial identical), and reports each player's win
+    // frequency. A trial that runs past `MAX_ANALYSIS_TURNS` without a
+    // winner, or whose bump chain loops, is simply not counted as a win
+    // for anyone.
+    fn analyze(&self, trials: usize) -> String {
+        let mut wins: Vec<u32> = vec![0; self.players.len()];
+
+        for _ in 0..trials {
+            let mut trial = self.clone();
+            trial.dice = DiceMode::Random { low: 1, high: 6 };
+            trial.rng = None;
+
+            'playthrough: for _ in 0..MAX_ANALYSIS_TURNS {
+                for (which_player, win) in wins.iter_mut().enumerate() {
+                    match trial.player_turn(which_player) {
+                        TurnOutcome::Won => {
+                            *win += 1;
+                            break 'playthrough
+                        }
+                        TurnOutcome::LoopDetected => break 'playthrough,
+                        TurnOutcome::Moved => {}
+                    }
+                }
+            }
+        }
+
+        let probs: Vec<f64> = wins.iter().map(|&w| w as f64 / trials as f64).collect();
+        format_win_probs(&probs)
+    }
+
+    // Takes up to `MAX_BALANCE_CANDIDATES` unoccupied `Plain` cells (spread
+    // evenly by `sample_evenly` when there are more), tries each as a
+    // ladder-or-snake start paired with every other one as its end, and uses
+    // `expectimax` to estimate each player's win probability on the
+    // resulting board. Returns the placement whose win probabilities are
+    // closest together (smallest gap between the luckiest and unluckiest
+    // player), along with that estimate - or `None` if there's nowhere
+    // left to place a feature.
+    pub fn fairest_next_placement(&self, max_turns: usize) -> Option<(Placement, Vec<f64>)> {
+        use CellProperty::{LadderStart, SnakeStart};
+
+        // With no players, `expectimax` has no active player to search
+        // from - there is nothing to balance.
+        if self.players.is_empty() {
+            return None
+        }
+
+        let occupied: HashSet<CellIx> = self.players.iter().map(|p| p.loc).collect();
+        let unoccupied: Vec<CellIx> = self.board.plain_cells().into_iter()
+            .filter(|c| !occupied.contains(c))
+            .collect();
+        let candidates = sample_evenly(&unoccupied, MAX_BALANCE_CANDIDATES);
+
+        let mut best: Option<(Placement, Vec<f64>, f64)> = None;
+
+        for &starts in &candidates {
+            for &ends in &candidates {
+                if starts == ends { continue }
+
+                for kind in [FeatureKind::Ladder, FeatureKind::Snake] {
+                    let mut board = self.board.clone();
+                    board.set_cell_prop(starts, match kind {
+                        FeatureKind::Ladder => LadderStart { end: ends },
+                        FeatureKind::Snake => SnakeStart { end: ends },
+                    });
+
+                    let probs = self.win_probabilities(&board, max_turns);
+                    let gap = probs.iter().cloned().fold(f64::NEG_INFINITY, f64::max)
+                        - probs.iter().cloned().fold(f64::INFINITY, f64::min);
+
+                    let placement = Placement { kind, starts, ends };
+                    if best.as_ref().is_none_or(|&(_, _, best_gap)| gap < best_gap) {
+                        best = Some((placement, probs, gap));
+                    }
+                }
+            }
+        }
+
+        best.map(|(placement, probs, _)| (placement, probs))
+    }
+
+    // Runs the expectimax search of `board` from the current player
+    // positions/powerups, bounded to `max_turns` plies deep.
+    fn win_probabilities(&self, board: &BoardConfig, max_turns: usize) -> Vec<f64> {
+        let mut scratch = GameState {
+            board: board.clone(),
+            players: self.players.clone(),
+            dice: DiceMode::default(),
+            rng: None,
+            turn: 0,
+        };
+        let start = Position {
+            locs: self.players.iter().map(|p| p.loc).collect(),
+            powerups: self.players.iter().map(|p| p.powerup.clone()).collect(),
+            active: 0,
+            dice_phase: 0,
+        };
+        let mut cache = HashMap::new();
+        expectimax(&mut scratch, &self.dice, &start, max_turns, &mut cache)
+    }
+}
+
+// Wraps a `GameState` with a running tally of wins, so a `reset` command
+// can start a fresh game without losing the score of a best-of series
+// played from one piped input or interactive session.
+#[derive(Debug, Default)]
+struct Session {
+    game: GameState,
+    wins: HashMap<char, u32>,
+    // Set once the current game's winner has been tallied, so a `turns`
+    // command run again after a win (or any other later command) doesn't
+    // count the same win twice. Cleared by `reset`.
+    recorded: bool,
+}
+
+impl Session {
+    fn apply(&mut self, command: Command) -> Result<(), ParseError> {
+        match command {
+            Command::Reset => {
+                self.game = GameState::default();
+                self.recorded = false;
+            }
+            Command::Scoreboard => println!("{}", self.scoreboard()),
+            other => {
+                self.game.apply(other)?;
+                match self.game.winner() {
+                    Some(who) if !self.recorded => {
+                        *self.wins.entry(who).or_insert(0) += 1;
+                        self.recorded = true;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn scoreboard(&self) -> String {
+        if self.wins.is_empty() {
+            return "no wins yet".into()
+        }
+        let mut names: Vec<&char> = self.wins.keys().collect();
+        names.sort();
+        names.iter()
+            .map(|&&name| format!("{}: {}", name, self.wins[&name]))
+            .collect::<Vec<_>>()
+            .join("  ")
+    }
+}
+
+// The dice can either cycle through a fixed, scripted sequence (as given by
+// a `dice 1 2 2 2 2` command) or draw randomly from a range on every roll
+// (as given by a `dice random 1 6` or `dice random 6` command).
+#[derive(PartialEq, Eq, Debug, Clone)]
+enum DiceMode {
+    Fixed(Vec<DieDots>),
+    Random { low: DieDots, high: DieDots },
+}
+
+impl Default for DiceMode {
+    fn default() -> Self {
+        DiceMode::Fixed(vec![])
+    }
+}
+
+// What came of a single player's turn: either they moved (possibly
+// bumping others along the way), they reached the winning cell, or the
+// bump chain that resolved their move revisited a cell, meaning the
+// board is degenerate and the turn was abandoned rather than hung.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum TurnOutcome {
+    Moved,
+    Won,
+    LoopDetected,
 }
 
 #[derive(PartialEq, Eq, Clone, Debug, Default)]
@@ -266,7 +554,7 @@ impl PlayerState {
 }
 
 
-#[derive(PartialEq, Eq, Debug, Default)]
+#[derive(PartialEq, Eq, Debug, Default, Clone)]
 struct BoardConfig {
     columns: usize,
     rows: usize,
@@ -274,14 +562,22 @@ struct BoardConfig {
 }
 
 impl BoardConfig {
-    fn set_size(&mut self, columns: CellIx, rows: CellIx) {
+    fn set_size(&mut self, columns: CellIx, rows: CellIx) -> Result<(), ParseError> {
         use CellProperty::*;
 
-        assert!(columns * rows <= MAX_CELLS);
+        if columns == 0 || rows == 0 {
+            return Err(ParseError::BoardTooSmall)
+        }
+        let total = columns.checked_mul(rows)
+            .ok_or(ParseError::DimensionsOverflow { columns, rows })?;
+        if total > MAX_CELLS {
+            return Err(ParseError::BoardTooLarge { cells: total })
+        }
         self.columns = columns;
         self.rows = rows;
-        self.cells = vec![Plain; columns * rows];
-        self.cells[columns * rows - 1] = Winning;
+        self.cells = vec![Plain; total];
+        self.cells[total - 1] = Winning;
+        Ok(())
     }
 
     fn size(&self) -> CellIx {
@@ -292,6 +588,27 @@ impl BoardConfig {
         self.cells[ix - 1] = cp
     }
 
+    // A cell index is only usable once the board is big enough to
+    // contain it - ladder, snake, and powerup commands all land here
+    // before touching `cells`, instead of panicking on an out-of-range
+    // index.
+    fn check_cell(&self, ix: CellIx) -> Result<(), ParseError> {
+        if ix == 0 || ix > self.size() {
+            Err(ParseError::CellOutOfBounds { cell: ix, board_size: self.size() })
+        } else {
+            Ok(())
+        }
+    }
+
+    // The cells still available for a new snake, ladder, or powerup:
+    // anywhere not already some other feature or the winning cell.
+    fn plain_cells(&self) -> Vec<CellIx> {
+        self.cells.iter().enumerate()
+            .filter(|&(_, cp)| *cp == CellProperty::Plain)
+            .map(|(ix, _)| ix + 1)
+            .collect()
+    }
+
     // 2. The cell numbering starts at the bottom left, and loops back
     // and forth.
     fn back_and_forth(&self, row: usize, column: usize) -> CellIx {
@@ -383,8 +700,150 @@ fn player_name(pix: PlayerIx) -> char {
     (('A' as u8) + (pix as u8)) as char
 }
 
+// Shared by `analyze` and `fairest_next_placement`: renders one win
+// probability per player, in the `A 0.41 / B 0.59` style.
+fn format_win_probs(probs: &[f64]) -> String {
+    probs.iter().enumerate()
+        .map(|(ix, &p)| format!("{} {:.2}", player_name(ix), p))
+        .collect::<Vec<_>>()
+        .join(" / ")
+}
+
+// Used by `fairest_next_placement` to bound its candidate set: if `items`
+// is already within `limit` (or `limit` is 0), returns it unchanged; otherwise picks
+// `limit` entries spread evenly across it (by index), so a big board
+// samples cells from end to end instead of just the first `limit`.
+fn sample_evenly(items: &[CellIx], limit: usize) -> Vec<CellIx> {
+    if items.len() <= limit || limit == 0 {
+        return items.to_vec()
+    }
+    (0..limit)
+        .map(|i| items[i * (items.len() - 1) / (limit - 1).max(1)])
+        .collect()
+}
+
 
-#[derive(PartialEq, Eq, Debug, Clone)]
+// ---- Board-balance solver ----
+//
+// `fairest_next_placement` looks for the ladder or snake placement that
+// levels the playing field the most. It scores each candidate with
+// `expectimax`: the die roll that resolves a player's move is a chance
+// node, and the position after it (another player's turn, or a win) is
+// evaluated recursively and averaged by the die face's probability.
+
+// A hashable snapshot of whatever the search needs to remember about a
+// position: every player's cell and powerup, whose move is next, and
+// which phase of the dice cycle comes up. Two branches that land on the
+// same position (at the same remaining depth) share one evaluation
+// instead of walking the same subtree twice.
+#[derive(PartialEq, Eq, Hash, Clone)]
+struct Position {
+    locs: Vec<CellIx>,
+    powerups: Vec<Option<PowerType>>,
+    active: PlayerIx,
+    dice_phase: usize,
+}
+
+// What can be placed on a candidate cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureKind { Ladder, Snake }
+
+impl FeatureKind {
+    fn command_word(self) -> &'static str {
+        match self {
+            FeatureKind::Ladder => "ladder",
+            FeatureKind::Snake => "snake",
+        }
+    }
+}
+
+// A candidate ladder or snake, as `fairest_next_placement` would add it
+// with a `ladder starts ends` or `snake starts ends` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub kind: FeatureKind,
+    pub starts: CellIx,
+    pub ends: CellIx,
+}
+
+// The possible die faces at a given point in `dice`'s cycle, each paired
+// with its probability. `DiceMode::Fixed` has exactly one face at every
+// phase (no real chance at all, but it fits the same shape); an
+// unconfigured dice falls back to a standard uniform 1..=6 die so the
+// solver stays usable even before a `dice` command has run.
+fn dice_faces(dice: &DiceMode, phase: usize) -> Vec<(DieDots, f64)> {
+    match dice {
+        DiceMode::Fixed(ds) if !ds.is_empty() =>
+            vec![(ds[phase % ds.len()], 1.0)],
+        DiceMode::Fixed(_) =>
+            (1..=6).map(|f| (f, 1.0 / 6.0)).collect(),
+        DiceMode::Random { low, high } => {
+            let faces = (*high - *low + 1) as f64;
+            (*low..=*high).map(|f| (f, 1.0 / faces)).collect()
+        }
+    }
+}
+
+// Expectimax search: `scratch` carries the candidate board under test and
+// is repointed at `position`'s players before every die face is tried, so
+// the (potentially large) board itself is only ever cloned once, by the
+// caller. `turns_left` bounds the recursion; reaching it with no winner
+// credits nobody, so each value is a player's chance of winning within
+// the bound. A candidate on which nobody can win within the bound scores
+// all zeros, which `fairest_next_placement` sees as a gap of 0.
+fn expectimax(
+    scratch: &mut GameState,
+    dice: &DiceMode,
+    position: &Position,
+    turns_left: usize,
+    cache: &mut HashMap<(Position, usize), Vec<f64>>,
+) -> Vec<f64> {
+    let n = position.locs.len();
+    if turns_left == 0 {
+        return vec![0.0; n]
+    }
+
+    let key = (position.clone(), turns_left);
+    if let Some(value) = cache.get(&key) {
+        return value.clone()
+    }
+
+    let mut value = vec![0.0; n];
+    for (face, prob) in dice_faces(dice, position.dice_phase) {
+        scratch.players = position.locs.iter().zip(&position.powerups)
+            .map(|(&loc, pu)| PlayerState { loc, powerup: pu.clone() })
+            .collect();
+        scratch.dice = DiceMode::Fixed(vec![face]);
+
+        let child = match scratch.player_turn(position.active) {
+            TurnOutcome::Won => {
+                let mut v = vec![0.0; n];
+                v[position.active] = 1.0;
+                v
+            }
+            TurnOutcome::LoopDetected => vec![0.0; n],
+            TurnOutcome::Moved => {
+                let next = Position {
+                    locs: scratch.players.iter().map(|p| p.loc).collect(),
+                    powerups: scratch.players.iter().map(|p| p.powerup.clone()).collect(),
+                    active: (position.active + 1) % n,
+                    dice_phase: position.dice_phase + 1,
+                };
+                expectimax(scratch, dice, &next, turns_left - 1, cache)
+            }
+        };
+
+        for (v, c) in value.iter_mut().zip(child.iter()) {
+            *v += prob * c;
+        }
+    }
+
+    cache.insert(key, value.clone());
+    value
+}
+
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
 enum PowerType {
     // 1. powerup escalator 6 9 sub-command: makes the next ladder
     // cell a player steps onto twice as boosting - i.e. they move
@@ -403,14 +862,14 @@ enum PowerType {
 }
 
 impl PowerType {
-    fn new(name: &str) -> PowerType {
+    fn new(name: &str) -> Result<PowerType, ParseError> {
         use PowerType::*;
 
         match name {
-            "escalator" => Escalator,
-            "antivenom" => Antivenom,
-            "double" => Double,
-            _ => panic!("bad powerup: {}", name)
+            "escalator" => Ok(Escalator),
+            "antivenom" => Ok(Antivenom),
+            "double" => Ok(Double),
+            _ => Err(ParseError::UnknownPowerUp { name: name.into() })
         }
     }
 
@@ -424,6 +883,56 @@ impl PowerType {
 }
 
 
+// Everything that can go wrong turning a line of input into game state:
+// malformed commands caught by `Command::readFrom`, and otherwise
+// well-formed ones `GameState::apply` finds don't fit the board or
+// player count built up so far.
+#[derive(PartialEq, Eq, Debug)]
+pub enum ParseError {
+    UnknownCommand { keyword: String },
+    WrongArity { keyword: String, got: usize },
+    BadNumber { keyword: String, text: String },
+    UnknownPowerUp { name: String },
+    BoardTooLarge { cells: usize },
+    TooManyPlayers { requested: PlayerIx },
+    CellOutOfBounds { cell: CellIx, board_size: CellIx },
+    NoPlayers,
+    BoardTooSmall,
+    InvalidRange { low: DieDots, high: DieDots },
+    DimensionsOverflow { columns: CellIx, rows: CellIx },
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use ParseError::*;
+        match self {
+            UnknownCommand { keyword } =>
+                write!(f, "unknown command '{}'", keyword),
+            WrongArity { keyword, got } =>
+                write!(f, "wrong number of arguments for '{}' ({} given)", keyword, got),
+            BadNumber { keyword, text } =>
+                write!(f, "'{}' is not a valid number for '{}'", text, keyword),
+            UnknownPowerUp { name } =>
+                write!(f, "unknown powerup '{}'", name),
+            BoardTooLarge { cells } =>
+                write!(f, "a board of {} cells exceeds the {} cell limit", cells, MAX_CELLS),
+            TooManyPlayers { requested } =>
+                write!(f, "{} players requested, but at most {} are allowed", requested, MAX_PLAYERS),
+            CellOutOfBounds { cell, board_size } =>
+                write!(f, "cell {} is out of bounds for a {}-cell board", cell, board_size),
+            NoPlayers =>
+                write!(f, "no players to act on; run a 'players' command first"),
+            BoardTooSmall =>
+                write!(f, "a board must have at least 1 column and 1 row"),
+            InvalidRange { low, high } =>
+                write!(f, "dice range {}..{} is empty (low must not exceed high)", low, high),
+            DimensionsOverflow { columns, rows } =>
+                write!(f, "a board of {} columns by {} rows overflows", columns, rows),
+        }
+    }
+}
+
+
 #[derive(PartialEq, Eq, Debug)]
 enum Command {
 
@@ -439,6 +948,15 @@ enum Command {
     // rolls.
     Dice (Vec<DieDots>),
 
+    // 3b. dice random 1 6 command: rolls uniformly in 1..=6 from here
+    // on; dice random 6 is shorthand for the same range starting at 1.
+    DiceRandom { low: DieDots, high: DieDots },
+
+    // 3c. seed 42 command: switches random dice rolls to a reproducible
+    // `StdRng` seeded with the given value, instead of the OS's entropy
+    // source.
+    Seed (u64),
+
     // 4. ladder 5 11 command: creates a ladder that starts at the
     // first number and ends at the second number
     Ladder { starts: CellIx, ends: CellIx },
@@ -453,55 +971,109 @@ enum Command {
 
     // 7. turns 10 command: plays the specified number of turns (or
     // until a player wins the game).
-    Turns (usize)
+    Turns (usize),
+
+    // 8. analyze 1000 command: runs that many independent, randomly
+    // diced playthroughs of the board as currently configured and
+    // prints each player's win frequency.
+    Analyze (usize),
+
+    // 9. balance 40 command: searches unoccupied cells for the ladder or
+    // snake placement that makes the game fairest, using an expectimax
+    // search bounded to 40 plies deep, and prints the placement found
+    // alongside its estimated win probabilities.
+    Balance (usize),
+
+    // 10. reset command: clears the board and players back to a fresh
+    // game, but keeps the session's accumulated win tally.
+    Reset,
+
+    // 11. scoreboard command: prints the session's accumulated win count
+    // for each player who has won at least one game so far.
+    Scoreboard,
 }
 
 
+// The keywords `readFrom` understands - used to tell an unknown command
+// apart from a known one used with the wrong number of arguments.
+const KNOWN_KEYWORDS: &[&str] = &[
+    "board", "players", "dice", "seed", "ladder", "snake", "powerup",
+    "turns", "analyze", "balance", "reset", "scoreboard",
+];
+
 impl Command {
     // 2. Your module must have a readFrom function that accepts a string.
     // A command is a keyword followed by one or more parameters
     // separated by a single space.
 
-    // 6. Assume the input is perfectly legal, with no invalid
-    // commands, extra spaces, invalid numbers, etc. Additionally
-    // assume the set-up board will not produce any bump loops during
-    // play.
+    // 6. A malformed line - an unknown keyword, the wrong number of
+    // arguments, a number that doesn't parse, or an unrecognized powerup
+    // name - is reported as a `ParseError` rather than panicking, so one
+    // bad line doesn't take down an otherwise-valid session.
     #[allow(non_snake_case)]
-    fn readFrom(line: String) -> Command {
+    fn readFrom(line: String) -> Result<Command, ParseError> {
         use Command::*;
 
-        let num8 = |txt: &str| txt
-            .parse::<u8>().expect("bad number");
-        let num = |txt: &str| txt
-            .parse::<CellIx>().expect("bad number");
+        let num8 = |keyword: &str, txt: &str| txt.parse::<u8>()
+            .map_err(|_| ParseError::BadNumber { keyword: keyword.into(), text: txt.into() });
+        let num = |keyword: &str, txt: &str| txt.parse::<CellIx>()
+            .map_err(|_| ParseError::BadNumber { keyword: keyword.into(), text: txt.into() });
 
         let mut parameters = line.split(' ');
-        let keyword = parameters.next().expect("no keyword");
+        let keyword = parameters.next().unwrap_or("");
         match (keyword, parameters.collect::<Vec<_>>()) {
             ("board", ref ps) if ps.len() == 2 =>
-                Board{ columns: num(ps[0]),
-                       rows: num(ps[1]) },
+                Ok(Board{ columns: num("board", ps[0])?,
+                          rows: num("board", ps[1])? }),
 
             ("players", ref ps) if ps.len() == 1 =>
-                Players(num8(ps[0]) as PlayerIx),
+                Ok(Players(num8("players", ps[0])? as PlayerIx)),
+
+            ("dice", ref ps) if ps.len() == 3 && ps[0] == "random" =>
+                Ok(DiceRandom { low: num8("dice", ps[1])?, high: num8("dice", ps[2])? }),
+
+            ("dice", ref ps) if ps.len() == 2 && ps[0] == "random" =>
+                Ok(DiceRandom { low: 1, high: num8("dice", ps[1])? }),
 
-            ("dice", ps) => Dice(ps.iter().map(|s| num8(*s)).collect()),
+            ("dice", ref ps) if !ps.is_empty() => {
+                let mut ds = Vec::with_capacity(ps.len());
+                for s in ps { ds.push(num8("dice", s)?) }
+                Ok(Dice(ds))
+            }
+
+            ("seed", ref ps) if ps.len() == 1 =>
+                Ok(Seed(ps[0].parse::<u64>()
+                    .map_err(|_| ParseError::BadNumber { keyword: "seed".into(), text: ps[0].into() })?)),
 
             ("ladder", ref ps) if ps.len() == 2 =>
-                Ladder { starts: num(ps[0]),
-                         ends: num(ps[1]) },
+                Ok(Ladder { starts: num("ladder", ps[0])?,
+                            ends: num("ladder", ps[1])? }),
 
             ("snake", ref ps) if ps.len() == 2 =>
-                Snake { starts: num(ps[0]),
-                        ends: num(ps[1]) },
+                Ok(Snake { starts: num("snake", ps[0])?,
+                           ends: num("snake", ps[1])? }),
+
+            ("powerup", ref ps) if !ps.is_empty() => {
+                let typ = PowerType::new(ps[0])?;
+                let mut cells = Vec::with_capacity(ps.len() - 1);
+                for s in &ps[1..] { cells.push(num("powerup", s)?) }
+                Ok(PowerUps { typ, cells })
+            }
+
+            ("turns", ref ps) if ps.len() == 1 => Ok(Turns(num("turns", ps[0])?)),
+
+            ("analyze", ref ps) if ps.len() == 1 => Ok(Analyze(num("analyze", ps[0])?)),
+
+            ("balance", ref ps) if ps.len() == 1 => Ok(Balance(num("balance", ps[0])?)),
 
-            ("powerup", ref ps) => PowerUps {
-                typ: PowerType::new(ps[0]),
-                cells: ps[1..].iter().map(|s| num(*s)).collect() },
+            ("reset", ref ps) if ps.is_empty() => Ok(Reset),
 
-            ("turns", ref ps) if ps.len() == 1 => Turns(num(ps[0])),
+            ("scoreboard", ref ps) if ps.is_empty() => Ok(Scoreboard),
 
-            _ => panic!("bad command: {}", line)
+            (kw, ref ps) if KNOWN_KEYWORDS.contains(&kw) =>
+                Err(ParseError::WrongArity { keyword: kw.into(), got: ps.len() }),
+
+            (kw, _) => Err(ParseError::UnknownCommand { keyword: kw.into() })
         }
     }
 }
@@ -529,7 +1101,7 @@ mod test {
     fn command_parse1() {
         use Command::*;
         assert!(Command::readFrom("board 3 4".into()) ==
-                Board { columns: 3, rows: 4});
+                Ok(Board { columns: 3, rows: 4}));
     }
 
     #[test]
@@ -539,23 +1111,23 @@ mod test {
         let lines: Vec<_> = SAMPLE_INPUT.trim().lines().collect();
 
         assert!(Command::readFrom(lines[0].into()) ==
-                Board { columns: 3, rows: 4});
+                Ok(Board { columns: 3, rows: 4}));
         assert!(Command::readFrom(lines[1].into()) ==
-                Players(2));
+                Ok(Players(2)));
         assert!(Command::readFrom(lines[2].into()) ==
-                Dice(vec!(1, 2, 2, 2, 2)));
+                Ok(Dice(vec!(1, 2, 2, 2, 2))));
         assert!(Command::readFrom(lines[3].into()) ==
-                Ladder{ starts: 5, ends: 11});
+                Ok(Ladder{ starts: 5, ends: 11}));
         assert!(Command::readFrom(lines[4].into()) ==
-                Snake{ starts: 8, ends: 4});
+                Ok(Snake{ starts: 8, ends: 4}));
         assert!(Command::readFrom(lines[5].into()) ==
-                PowerUps{ typ: Escalator, cells: vec!(6, 9) });
+                Ok(PowerUps{ typ: Escalator, cells: vec!(6, 9) }));
         assert!(Command::readFrom(lines[6].into()) ==
-                PowerUps{ typ: Antivenom, cells: vec!(7) });
+                Ok(PowerUps{ typ: Antivenom, cells: vec!(7) }));
         assert!(Command::readFrom(lines[7].into()) ==
-                PowerUps{ typ: Double, cells: vec!(4) });
+                Ok(PowerUps{ typ: Double, cells: vec!(4) }));
         assert!(Command::readFrom(lines[8].into()) ==
-                Turns(10));
+                Ok(Turns(10)));
 
     }
 
@@ -564,12 +1136,223 @@ mod test {
         let mut game = GameState::default();
 
         for line in SAMPLE_INPUT.trim().lines() {
-            let cmd = Command::readFrom(line.into());
-            game.apply(cmd);
+            let cmd = Command::readFrom(line.into()).unwrap();
+            game.apply(cmd).unwrap();
         }
 
         println!("{}", game.print());
         assert!(game.print() == RESULTING_OUTPUT.trim());
     }
 
+    #[test]
+    fn read_from_reports_parse_errors_instead_of_panicking() {
+        assert!(Command::readFrom("fly 3 4".into()) ==
+                Err(ParseError::UnknownCommand { keyword: "fly".into() }));
+        assert!(Command::readFrom("board 3".into()) ==
+                Err(ParseError::WrongArity { keyword: "board".into(), got: 1 }));
+        assert!(Command::readFrom("board x 4".into()) ==
+                Err(ParseError::BadNumber { keyword: "board".into(), text: "x".into() }));
+        assert!(Command::readFrom("powerup flying 3".into()) ==
+                Err(ParseError::UnknownPowerUp { name: "flying".into() }));
+        // A bare `dice` with no rolls at all is wrong arity, not an empty
+        // fixed sequence - an empty sequence would panic on the first
+        // roll (`ds[turn % ds.len()]` divides by zero).
+        assert!(Command::readFrom("dice".into()) ==
+                Err(ParseError::WrongArity { keyword: "dice".into(), got: 0 }));
+    }
+
+    #[test]
+    fn apply_reports_semantic_errors_instead_of_panicking() {
+        let mut game = GameState::default();
+        game.apply(Command::readFrom("board 3 4".into()).unwrap()).unwrap();
+
+        assert!(game.apply(Command::readFrom("players 27".into()).unwrap()) ==
+                Err(ParseError::TooManyPlayers { requested: 27 }));
+        assert!(game.apply(Command::readFrom("ladder 5 99".into()).unwrap()) ==
+                Err(ParseError::CellOutOfBounds { cell: 99, board_size: 12 }));
+        assert!(game.apply(Command::readFrom("board 0 4".into()).unwrap()) ==
+                Err(ParseError::BoardTooSmall));
+        assert!(game.apply(Command::readFrom("dice random 6 1".into()).unwrap()) ==
+                Err(ParseError::InvalidRange { low: 6, high: 1 }));
+        // `columns * rows` must not be computed before this is checked,
+        // or it overflows `usize` and panics instead of erroring.
+        assert!(game.apply(Command::readFrom("board 99999999999 99999999999".into()).unwrap()) ==
+                Err(ParseError::DimensionsOverflow { columns: 99999999999, rows: 99999999999 }));
+
+        // A failed command leaves previously-applied state untouched.
+        assert!(game.board.size() == 12);
+        assert!(game.apply(Command::readFrom("players 2".into()).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn parse_dice_random() {
+        use Command::*;
+        assert!(Command::readFrom("dice random 1 6".into()) ==
+                Ok(DiceRandom { low: 1, high: 6 }));
+        assert!(Command::readFrom("dice random 6".into()) ==
+                Ok(DiceRandom { low: 1, high: 6 }));
+        assert!(Command::readFrom("seed 42".into()) ==
+                Ok(Seed(42)));
+    }
+
+    #[test]
+    fn seeded_random_dice_is_reproducible() {
+        let mut game1 = GameState::default();
+        game1.apply(Command::readFrom("dice random 1 6".into()).unwrap()).unwrap();
+        game1.apply(Command::readFrom("seed 42".into()).unwrap()).unwrap();
+
+        let mut game2 = GameState::default();
+        game2.apply(Command::readFrom("dice random 1 6".into()).unwrap()).unwrap();
+        game2.apply(Command::readFrom("seed 42".into()).unwrap()).unwrap();
+
+        let rolls1: Vec<_> = (0..20).map(|_| game1.roll_dice()).collect();
+        let rolls2: Vec<_> = (0..20).map(|_| game2.roll_dice()).collect();
+        assert!(rolls1 == rolls2);
+        assert!(rolls1.iter().all(|&d| (1..=6).contains(&d)));
+    }
+
+    // Two players parked on the same cell, with a snake that bounces
+    // straight back to it, would bump each other back and forth forever
+    // under the old code - `player_turn` must notice the repeat instead.
+    #[test]
+    fn cyclic_bump_chain_is_detected_instead_of_hanging() {
+        use CellProperty::SnakeStart;
+
+        let mut game = GameState::default();
+        game.board.set_size(1, 10).unwrap();
+        game.board.set_cell_prop(5, SnakeStart { end: 4 });
+        game.players = vec![
+            PlayerState { loc: 4, ..PlayerState::default() },
+            PlayerState { loc: 4, ..PlayerState::default() },
+        ];
+        game.dice = DiceMode::Fixed(vec![1]);
+
+        assert!(game.player_turn(0) == TurnOutcome::LoopDetected);
+    }
+
+    #[test]
+    fn parse_analyze() {
+        use Command::*;
+        assert!(Command::readFrom("analyze 500".into()) ==
+                Ok(Analyze(500)));
+    }
+
+    #[test]
+    fn analyze_reports_plausible_win_frequencies() {
+        let mut game = GameState::default();
+        game.board.set_size(1, 4).unwrap();
+        game.players = vec![PlayerState::default(), PlayerState::default()];
+
+        let trials = 200;
+        let report = game.analyze(trials);
+
+        let freqs: Vec<f64> = report.split(" / ")
+            .map(|part| part.split(' ').nth(1).unwrap().parse().unwrap())
+            .collect();
+        assert!(freqs.len() == 2);
+        // A 4-cell board is won within a handful of turns almost always,
+        // so nearly every trial should end with some player winning.
+        assert!(freqs.iter().sum::<f64>() > 0.9);
+    }
+
+    #[test]
+    fn parse_balance() {
+        use Command::*;
+        assert!(Command::readFrom("balance 40".into()) ==
+                Ok(Balance(40)));
+    }
+
+    #[test]
+    fn fairest_next_placement_suggests_a_candidate() {
+        let mut game = GameState::default();
+        game.board.set_size(1, 6).unwrap();
+        game.players = vec![PlayerState::default(), PlayerState::default()];
+
+        let (placement, probs) = game.fairest_next_placement(10)
+            .expect("a 6-cell board has plain cells left to place on");
+
+        assert!(placement.starts != placement.ends);
+        assert!(probs.len() == 2);
+    }
+
+    #[test]
+    fn balance_with_no_players_is_an_error_not_a_panic() {
+        let mut game = GameState::default();
+        game.board.set_size(1, 6).unwrap();
+
+        assert!(game.fairest_next_placement(10).is_none());
+        assert!(game.apply(Command::readFrom("balance 10".into()).unwrap()) ==
+                Err(ParseError::NoPlayers));
+    }
+
+    #[test]
+    fn sample_evenly_spans_the_whole_list_within_the_limit() {
+        let items: Vec<CellIx> = (1..=100).collect();
+        let sample = sample_evenly(&items, 10);
+
+        assert!(sample.len() == 10);
+        assert!(sample[0] == 1);
+        assert!(sample[9] == 100);
+        // No duplicate picks, so the search budget isn't wasted re-running
+        // the same candidate twice.
+        let distinct: HashSet<_> = sample.iter().collect();
+        assert!(distinct.len() == sample.len());
+
+        // Under the limit, nothing is dropped.
+        assert!(sample_evenly(&items[..5], 10) == items[..5]);
+    }
+
+    #[test]
+    fn balance_stays_fast_on_a_board_with_many_unoccupied_cells() {
+        let mut game = GameState::default();
+        game.board.set_size(5, 6).unwrap(); // 30 cells, ~28 unoccupied
+        game.players = vec![PlayerState::default(), PlayerState::default()];
+
+        // Without capping the candidate set, this is candidates * (candidates - 1) * 2
+        // independent expectimax searches, each itself exponential in the
+        // turn depth - tens of seconds on a board this size before the
+        // candidate cap, which defeats the point of an interactive
+        // `balance` command.
+        let start = std::time::Instant::now();
+        let result = game.fairest_next_placement(20);
+        assert!(result.is_some());
+        assert!(start.elapsed().as_secs() < 10);
+    }
+
+    #[test]
+    fn parse_reset_and_scoreboard() {
+        use Command::*;
+        assert!(Command::readFrom("reset".into()) == Ok(Reset));
+        assert!(Command::readFrom("scoreboard".into()) == Ok(Scoreboard));
+    }
+
+    #[test]
+    fn session_tallies_wins_across_games_and_reset_keeps_the_tally() {
+        let mut session = Session::default();
+        session.apply(Command::readFrom("board 1 1".into()).unwrap()).unwrap();
+        session.apply(Command::readFrom("players 2".into()).unwrap()).unwrap();
+        session.apply(Command::readFrom("dice 1".into()).unwrap()).unwrap();
+
+        // A 1-cell board: both players are placed on the winning cell, so A
+        // is the winner as soon as `players 2` runs.
+        session.apply(Command::readFrom("turns 1".into()).unwrap()).unwrap();
+        assert!(session.wins.get(&'A') == Some(&1));
+
+        // Further turns in the same already-won game must not double-count.
+        session.apply(Command::readFrom("turns 5".into()).unwrap()).unwrap();
+        assert!(session.wins.get(&'A') == Some(&1));
+
+        session.apply(Command::readFrom("reset".into()).unwrap()).unwrap();
+        assert!(session.wins.get(&'A') == Some(&1));
+        assert!(session.game.players.is_empty());
+
+        session.apply(Command::readFrom("board 1 1".into()).unwrap()).unwrap();
+        session.apply(Command::readFrom("players 2".into()).unwrap()).unwrap();
+        session.apply(Command::readFrom("dice 1".into()).unwrap()).unwrap();
+        session.apply(Command::readFrom("turns 1".into()).unwrap()).unwrap();
+        assert!(session.wins.get(&'A') == Some(&2));
+
+        assert!(session.scoreboard() == "A: 2");
+    }
+
 }